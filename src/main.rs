@@ -5,6 +5,8 @@
 #![no_main]
 #![allow(async_fn_in_trait)]
 use core::sync::atomic::Ordering;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::mutex::Mutex;
 use heapless::String;
 
 use cyw43_pio::PioSpi;
@@ -32,6 +34,14 @@ use dht11::DHT11;
 use temp_controller::{TempController, SHARED_HUMID, SHARED_TEMP};
 mod uart_cli;
 use uart_cli::uart_cli;
+mod mqtt;
+use mqtt::mqtt_task;
+#[cfg(feature = "wifi")]
+mod provisioning;
+#[cfg(feature = "wifi")]
+use provisioning::{load_credentials, run_provisioning, FLASH_SIZE};
+mod net_backend;
+use net_backend::NetDevice;
 
 bind_interrupts!(struct PIOIrqs {
     PIO0_IRQ_0 => PIOInterruptHandler<PIO0>;
@@ -42,11 +52,22 @@ bind_interrupts!(struct UARTIrqs {
     UART0_IRQ  => UARTInterruptHandler<UART0>;
 });
 
-static CONTROLLER: StaticCell<TempController> = StaticCell::new();
+/// Controller instance shared between the UART CLI, the MQTT subsystem and the
+/// control task. All setpoint/mode/hysteresis access goes through this mutex instead
+/// of the old raw-pointer aliasing.
+pub type SharedController = Mutex<ThreadModeRawMutex, TempController>;
 
-const WIFI_NETWORK: &str = include_str!("wifi_network");
-const WIFI_PASSWORD: &str = include_str!("wifi_password");
+static CONTROLLER: StaticCell<SharedController> = StaticCell::new();
 
+/// Identifier used to scope this unit's MQTT topics (`ac/<id>/...`).
+const AC_ID: &str = "picow-ac";
+/// DHCP hostname advertised to the router so the unit is discoverable by name.
+const HOSTNAME: &str = "picow-ac";
+/// Host and port of the MQTT broker the device pushes telemetry to.
+const MQTT_BROKER: embassy_net::Ipv4Address = embassy_net::Ipv4Address::new(192, 168, 1, 2);
+const MQTT_PORT: u16 = 1883;
+
+#[cfg(feature = "wifi")]
 #[embassy_executor::task]
 async fn wifi_task(
     runner: cyw43::Runner<'static, Output<'static>, PioSpi<'static, PIO0, 0, DMA_CH0>>,
@@ -55,7 +76,7 @@ async fn wifi_task(
 }
 
 #[embassy_executor::task]
-async fn net_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+async fn net_task(stack: &'static Stack<NetDevice>) -> ! {
     stack.run().await
 }
 
@@ -65,57 +86,93 @@ async fn main(spawner: Spawner) {
 
     let p = embassy_rp::init(Default::default());
 
-    let controller: &'static mut TempController = CONTROLLER.init(TempController::new(
+    let controller: &'static SharedController = &*CONTROLLER.init(Mutex::new(TempController::new(
         22,
         Duration::from_secs(10),
         Duration::from_secs(10),
-    ));
+    )));
 
-    // Safety: I don't care about race conditions.
-    let test1 = controller as *mut TempController;
-    let test2 = controller as *mut TempController;
+    #[cfg(feature = "wifi")]
+    let mut flash = embassy_rp::flash::Flash::<_, embassy_rp::flash::Async, FLASH_SIZE>::new(
+        p.FLASH, p.DMA_CH3,
+    );
 
     let config = uart::Config::default();
     let uart = uart::Uart::new(
         p.UART0, p.PIN_0, p.PIN_1, UARTIrqs, p.DMA_CH1, p.DMA_CH2, config,
     );
 
-    // let fw = include_bytes!("../cyw43-firmware/43439A0.bin");
-    // let clm = include_bytes!("../cyw43-firmware/43439A0_clm.bin");
-
-    // To make flashing faster for development, you may want to flash the firmwares independently
-    // at hardcoded addresses, instead of baking them into the program with `include_bytes!`:
-    //     probe-rs download 43439A0.bin --format bin --chip RP2040 --base-address 0x10100000
-    //     probe-rs download 43439A0_clm.bin --format bin --chip RP2040 --base-address 0x10140000
-    let fw = unsafe { core::slice::from_raw_parts(0x10100000 as *const u8, 230321) };
-    let clm = unsafe { core::slice::from_raw_parts(0x10140000 as *const u8, 4752) };
-
-    let pwr = Output::new(p.PIN_23, Level::Low);
-    let cs = Output::new(p.PIN_25, Level::High);
     let pio1 = Pio::new(p.PIO1, PIOIrqs);
 
-    let mut pio0 = Pio::new(p.PIO0, PIOIrqs);
-    let spi = PioSpi::new(
-        &mut pio0.common,
-        pio0.sm0,
-        pio0.irq0,
-        cs,
-        p.PIN_24,
-        p.PIN_29,
-        p.DMA_CH0,
-    );
-
-    static STATE: StaticCell<cyw43::State> = StaticCell::new();
-    let state = STATE.init(cyw43::State::new());
-    let (net_device, mut control, runner) = cyw43::new(state, pwr, spi, fw).await;
-    unwrap!(spawner.spawn(wifi_task(runner)));
-
-    control.init(clm).await;
-    control
-        .set_power_management(cyw43::PowerManagementMode::PowerSave)
-        .await;
-
-    let config = IPConfig::dhcpv4(Default::default());
+    // WiFi backend: bring up the cyw43 chip and keep its `control` handle for joining,
+    // provisioning and the link LED. The wired backend skips all of this.
+    #[cfg(feature = "wifi")]
+    let (net_device, mut control) = {
+        // let fw = include_bytes!("../cyw43-firmware/43439A0.bin");
+        // let clm = include_bytes!("../cyw43-firmware/43439A0_clm.bin");
+
+        // To make flashing faster for development, you may want to flash the firmwares independently
+        // at hardcoded addresses, instead of baking them into the program with `include_bytes!`:
+        //     probe-rs download 43439A0.bin --format bin --chip RP2040 --base-address 0x10100000
+        //     probe-rs download 43439A0_clm.bin --format bin --chip RP2040 --base-address 0x10140000
+        let fw = unsafe { core::slice::from_raw_parts(0x10100000 as *const u8, 230321) };
+        let clm = unsafe { core::slice::from_raw_parts(0x10140000 as *const u8, 4752) };
+
+        let pwr = Output::new(p.PIN_23, Level::Low);
+        let cs = Output::new(p.PIN_25, Level::High);
+
+        let mut pio0 = Pio::new(p.PIO0, PIOIrqs);
+        let spi = PioSpi::new(
+            &mut pio0.common,
+            pio0.sm0,
+            pio0.irq0,
+            cs,
+            p.PIN_24,
+            p.PIN_29,
+            p.DMA_CH0,
+        );
+
+        static STATE: StaticCell<cyw43::State> = StaticCell::new();
+        let state = STATE.init(cyw43::State::new());
+        let (net_device, mut control, runner) = cyw43::new(state, pwr, spi, fw).await;
+        unwrap!(spawner.spawn(wifi_task(runner)));
+
+        control.init(clm).await;
+        control
+            .set_power_management(cyw43::PowerManagementMode::PowerSave)
+            .await;
+        (net_device, control)
+    };
+
+    // Wired backend: a W5500 on SPI0 in MACRAW mode. No `join` step is needed — the
+    // link comes up as soon as a cable is plugged in.
+    #[cfg(feature = "w5500")]
+    let net_device = {
+        use embassy_rp::spi::{Config as SpiConfig, Spi};
+        use embedded_hal_bus::spi::ExclusiveDevice;
+
+        let cs = Output::new(p.PIN_17, Level::High);
+        let int = Output::new(p.PIN_21, Level::High);
+        let reset = Output::new(p.PIN_20, Level::High);
+        let spi = Spi::new(
+            p.SPI0,
+            p.PIN_18,
+            p.PIN_19,
+            p.PIN_16,
+            p.DMA_CH4,
+            p.DMA_CH5,
+            SpiConfig::default(),
+        );
+        let spi = unwrap!(ExclusiveDevice::new(spi, cs, embassy_time::Delay));
+        let mac = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let (net_device, runner) = net_backend::w5500::new(spi, int, reset, mac).await;
+        unwrap!(spawner.spawn(net_backend::w5500::ethernet_task(runner)));
+        net_device
+    };
+
+    let mut dhcp_config = embassy_net::DhcpConfig::default();
+    dhcp_config.hostname = Some(unwrap!(HOSTNAME.try_into()));
+    let config = IPConfig::dhcpv4(dhcp_config);
     //let config = embassy_net::Config::ipv4_static(embassy_net::StaticConfigV4 {
     //    address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
     //    dns_servers: Vec::new(),
@@ -126,7 +183,7 @@ async fn main(spawner: Spawner) {
     let seed = 0x0123_4567_89ab_cdef; // chosen by fair dice roll. guarenteed to be random.
 
     // Init network stack
-    static STACK: StaticCell<Stack<cyw43::NetDriver<'static>>> = StaticCell::new();
+    static STACK: StaticCell<Stack<NetDevice>> = StaticCell::new();
     static RESOURCES: StaticCell<StackResources<2>> = StaticCell::new();
     let stack = &*STACK.init(Stack::new(
         net_device,
@@ -135,16 +192,34 @@ async fn main(spawner: Spawner) {
         seed,
     ));
 
-    unwrap!(spawner.spawn(uart_cli(uart, stack, test1)));
+    unwrap!(spawner.spawn(uart_cli(uart, stack, controller)));
 
     unwrap!(spawner.spawn(net_task(stack)));
 
-    loop {
-        //control.join_open(WIFI_NETWORK).await;
-        match control.join_wpa2(WIFI_NETWORK, WIFI_PASSWORD).await {
-            Ok(_) => break,
-            Err(err) => {
-                info!("join failed with status={}", err.status);
+    unwrap!(spawner.spawn(mqtt_task(stack, controller)));
+
+    // Resolve credentials and join the network. Only the WiFi backend associates; the
+    // wired backend has no SSID to join.
+    #[cfg(feature = "wifi")]
+    {
+        let credentials = match load_credentials(&mut flash) {
+            Some(creds) => creds,
+            None => {
+                info!("no stored credentials, entering provisioning mode");
+                run_provisioning(&mut control, stack, &mut flash).await
+            }
+        };
+
+        loop {
+            //control.join_open(WIFI_NETWORK).await;
+            match control
+                .join_wpa2(credentials.ssid.as_str(), credentials.password.as_str())
+                .await
+            {
+                Ok(_) => break,
+                Err(err) => {
+                    info!("join failed with status={}", err.status);
+                }
             }
         }
     }
@@ -154,7 +229,7 @@ async fn main(spawner: Spawner) {
     while !stack.is_config_up() {
         Timer::after_millis(100).await;
     }
-    info!("DHCP is now up!");
+    info!("DHCP is now up! hostname={}", HOSTNAME);
 
     // And now we can use it!
 
@@ -176,13 +251,14 @@ async fn main(spawner: Spawner) {
     SHARED_HUMID.store(initial_humidity, Ordering::Relaxed);
 
     unwrap!(spawner.spawn(temp_controller::temp_controller_task(
-        dht11_ctl, test2, p.PIN_13,
+        dht11_ctl, controller, p.PIN_13,
     )));
 
     loop {
         let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
         socket.set_timeout(Some(Duration::from_secs(10)));
 
+        #[cfg(feature = "wifi")]
         control.gpio_set(0, false).await;
         info!("Listening on TCP:1234...");
         if let Err(e) = socket.accept(1234).await {
@@ -191,6 +267,7 @@ async fn main(spawner: Spawner) {
         }
 
         info!("Received connection from {:?}", socket.remote_endpoint());
+        #[cfg(feature = "wifi")]
         control.gpio_set(0, true).await;
 
         loop {
@@ -0,0 +1,168 @@
+//! MQTT telemetry subsystem.
+//!
+//! Connects to a fixed broker over an `embassy_net::tcp::TcpSocket`, publishes the
+//! latest `SHARED_TEMP`/`SHARED_HUMID` readings on a timer and subscribes to the
+//! setpoint topic so the controller target can be updated remotely. This turns the
+//! board into a push telemetry node instead of the poll-only TCP:1234 endpoint.
+
+use core::fmt::Write as _;
+use core::sync::atomic::Ordering;
+
+use defmt::*;
+use embassy_futures::select::{select, Either};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::Stack;
+use embassy_time::{Duration, Timer};
+use heapless::String;
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+use crate::net_backend::NetDevice;
+use crate::temp_controller::{SHARED_HUMID, SHARED_TEMP};
+use crate::{SharedController, AC_ID, MQTT_BROKER, MQTT_PORT};
+
+/// How often telemetry is published to the broker. Kept well under [`KEEPALIVE_SECS`]
+/// so each publish doubles as the keepalive and no separate PINGREQ is needed.
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(10);
+/// Keepalive negotiated in the CONNECT packet, in seconds.
+const KEEPALIVE_SECS: u16 = 60;
+
+#[embassy_executor::task]
+pub async fn mqtt_task(
+    stack: &'static Stack<NetDevice>,
+    controller: &'static SharedController,
+) -> ! {
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    // rust-mqtt needs its own framing buffers separate from the socket buffers.
+    let mut recv_buffer = [0; 512];
+    let mut write_buffer = [0; 512];
+
+    let mut temperature_topic = String::<64>::new();
+    let mut humidity_topic = String::<64>::new();
+    let mut setpoint_topic = String::<64>::new();
+    let _ = write!(temperature_topic, "ac/{}/temperature", AC_ID);
+    let _ = write!(humidity_topic, "ac/{}/humidity", AC_ID);
+    let _ = write!(setpoint_topic, "ac/{}/setpoint", AC_ID);
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        // The timeout must stay at/above the keepalive window: a shorter one would RST
+        // the idle connection between publishes. The publish cadence is driven by a
+        // timer raced against the read in `run_session`, not by this timeout.
+        socket.set_timeout(Some(Duration::from_secs(KEEPALIVE_SECS as u64)));
+
+        info!("MQTT: connecting to broker {}:{}", MQTT_BROKER, MQTT_PORT);
+        if let Err(e) = socket.connect((MQTT_BROKER, MQTT_PORT)).await {
+            warn!("MQTT: broker connect error: {:?}", e);
+            Timer::after_secs(5).await;
+            continue;
+        }
+
+        let mut config = ClientConfig::new(
+            rust_mqtt::client::client_config::MqttVersion::MQTTv5,
+            CountingRng(20000),
+        );
+        config.add_client_id(AC_ID);
+        config.keep_alive = KEEPALIVE_SECS;
+        config.max_packet_size = 512;
+
+        let mut client = MqttClient::new(
+            socket,
+            &mut write_buffer,
+            512,
+            &mut recv_buffer,
+            512,
+            config,
+        );
+
+        if let Err(e) = client.connect_to_broker().await {
+            warn!("MQTT: CONNECT failed: {:?}", e);
+            Timer::after_secs(5).await;
+            continue;
+        }
+        if let Err(e) = client.subscribe_to_topic(setpoint_topic.as_str()).await {
+            warn!("MQTT: subscribe failed: {:?}", e);
+            Timer::after_secs(5).await;
+            continue;
+        }
+        info!("MQTT: connected, publishing to ac/{}/...", AC_ID);
+
+        // Publish on every tick and service incoming setpoint writes in between.
+        if run_session(
+            &mut client,
+            controller,
+            &temperature_topic,
+            &humidity_topic,
+        )
+        .await
+        .is_err()
+        {
+            warn!("MQTT: session ended, reconnecting");
+        }
+
+        Timer::after_secs(5).await;
+    }
+}
+
+/// Drive a single connected session until the first socket/protocol error.
+///
+/// Each iteration races the publish timer against an inbound read. The socket timeout
+/// is kept at the keepalive window so an idle connection is not reset between ticks,
+/// and because `PUBLISH_INTERVAL` < `KEEPALIVE_SECS` every publish also serves as the
+/// keepalive, so no explicit PINGREQ is required.
+async fn run_session<'a>(
+    client: &mut MqttClient<'a, TcpSocket<'a>, 5, CountingRng>,
+    controller: &'static SharedController,
+    temperature_topic: &str,
+    humidity_topic: &str,
+) -> Result<(), ()> {
+    let mut payload = itoa::Buffer::new();
+    loop {
+        match select(Timer::after(PUBLISH_INTERVAL), client.receive_message()).await {
+            Either::First(_) => {
+                let temperature = SHARED_TEMP.load(Ordering::Relaxed);
+                let humidity = SHARED_HUMID.load(Ordering::Relaxed);
+                if client
+                    .send_message(
+                        temperature_topic,
+                        payload.format(temperature).as_bytes(),
+                        QualityOfService::QoS0,
+                        false,
+                    )
+                    .await
+                    .is_err()
+                {
+                    return Err(());
+                }
+                if client
+                    .send_message(
+                        humidity_topic,
+                        payload.format(humidity).as_bytes(),
+                        QualityOfService::QoS0,
+                        false,
+                    )
+                    .await
+                    .is_err()
+                {
+                    return Err(());
+                }
+            }
+            Either::Second(Ok((_topic, payload))) => {
+                if let Some(setpoint) = core::str::from_utf8(payload)
+                    .ok()
+                    .and_then(|s| s.trim().parse::<i32>().ok())
+                {
+                    info!("MQTT: new setpoint {}", setpoint);
+                    controller.lock().await.set_setpoint(setpoint);
+                }
+            }
+            Either::Second(Err(e)) => {
+                warn!("MQTT: receive error: {:?}", e);
+                return Err(());
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Compile-time selection of the network backend.
+//!
+//! The TCP server, temp controller and DHCP code are all written against the
+//! [`NetDevice`] alias rather than a concrete driver, so the same control stack runs
+//! either over the on-board cyw43 WiFi chip (`wifi` feature, the default) or over a
+//! wired WIZnet W5500 SPI Ethernet chip in MACRAW mode (`w5500` feature). Exactly one
+//! backend feature must be enabled.
+
+#[cfg(not(any(feature = "wifi", feature = "w5500")))]
+compile_error!("a network backend feature must be enabled: `wifi` or `w5500`");
+
+#[cfg(all(feature = "wifi", feature = "w5500"))]
+compile_error!("`wifi` and `w5500` are mutually exclusive; enable exactly one backend");
+
+/// The `embassy-net` driver the [`Stack`](embassy_net::Stack) is built over.
+#[cfg(feature = "wifi")]
+pub type NetDevice = cyw43::NetDriver<'static>;
+
+/// The `embassy-net` driver the [`Stack`](embassy_net::Stack) is built over.
+#[cfg(feature = "w5500")]
+pub type NetDevice = embassy_net_driver_channel::Device<'static, { w5500::MTU }>;
+
+#[cfg(feature = "w5500")]
+pub mod w5500 {
+    //! Wired backend: a W5500 driven in MACRAW mode over SPI.
+
+    use defmt::*;
+    use embassy_rp::gpio::Output;
+    use embassy_rp::peripherals::SPI0;
+    use embassy_rp::spi::{Async, Spi};
+    use embassy_time::Delay;
+    use embedded_hal_bus::spi::ExclusiveDevice;
+    use static_cell::StaticCell;
+
+    /// MACRAW frames carry a full Ethernet MTU plus framing headroom.
+    pub const MTU: usize = 1514;
+
+    type SpiBus = Spi<'static, SPI0, Async>;
+    type SpiDev = ExclusiveDevice<SpiBus, Output<'static>, Delay>;
+    type Runner = embassy_net_wiznet::Runner<'static, SpiDev, Output<'static>, Output<'static>>;
+
+    /// Background task that services the W5500 device, mirroring `wifi_task`.
+    #[embassy_executor::task]
+    pub async fn ethernet_task(runner: Runner) -> ! {
+        runner.run().await
+    }
+
+    /// Bring up the W5500 and return its `embassy-net` device half.
+    pub async fn new(
+        spi: SpiDev,
+        int: Output<'static>,
+        reset: Output<'static>,
+        mac: [u8; 6],
+    ) -> (super::NetDevice, Runner) {
+        static STATE: StaticCell<embassy_net_driver_channel::State<{ MTU }, 4, 4>> =
+            StaticCell::new();
+        let state = STATE.init(embassy_net_driver_channel::State::new());
+        let (device, runner) = unwrap!(
+            embassy_net_wiznet::new(mac, state, spi, int, reset).await,
+            "W5500 init failed"
+        );
+        (device, runner)
+    }
+}
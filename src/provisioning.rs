@@ -0,0 +1,298 @@
+//! Field provisioning of WiFi credentials.
+//!
+//! On boot `main` asks [`load_credentials`] whether a valid SSID/password pair is
+//! stored in the reserved flash sector. If so the normal station path runs
+//! unchanged. If not, [`run_provisioning`] brings cyw43 up as an open access point,
+//! serves a one-page HTML form over TCP:80, persists the submitted credentials to
+//! flash and resets the chip so the device comes back up in station mode. This lets
+//! the controller be configured in the field without a debug probe.
+
+use core::fmt::Write as _;
+
+use cortex_m::peripheral::SCB;
+use defmt::*;
+use embassy_futures::select::{select, Either};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{ConfigV4, IpEndpoint, Ipv4Address, Ipv4Cidr, StaticConfigV4, Stack};
+use embassy_rp::flash::{Async, Flash};
+use embassy_rp::peripherals::FLASH;
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use heapless::{String, Vec};
+
+/// Total flash size of the Pico W module.
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+/// Offset of the sector reserved for stored credentials (last 4 KiB of flash).
+const CREDS_OFFSET: u32 = (FLASH_SIZE - 4096) as u32;
+const ERASE_SIZE: u32 = 4096;
+/// Marks a written credential record so erased flash is not mistaken for one.
+const MAGIC: u32 = 0x4143_5756; // "ACWV"
+/// SSID used for the provisioning access point.
+const AP_SSID: &str = "picow-ac-setup";
+/// Static address the device gives itself while the provisioning AP is up.
+const AP_ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 4, 1);
+/// Single address leased to the client that connects to the AP.
+const AP_CLIENT: Ipv4Address = Ipv4Address::new(192, 168, 4, 2);
+/// Lease time handed out by the provisioning DHCP server, in seconds.
+const DHCP_LEASE_SECS: u32 = 3600;
+
+/// A stored WiFi network name and passphrase.
+pub struct Credentials {
+    pub ssid: String<32>,
+    pub password: String<64>,
+}
+
+/// Read the reserved sector and return the stored credentials if the record is valid.
+pub fn load_credentials(flash: &mut Flash<'static, FLASH, Async, FLASH_SIZE>) -> Option<Credentials> {
+    let mut buf = [0u8; 4 + 1 + 1 + 32 + 64];
+    if flash.blocking_read(CREDS_OFFSET, &mut buf).is_err() {
+        return None;
+    }
+    if u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) != MAGIC {
+        return None;
+    }
+    let ssid_len = buf[4] as usize;
+    let pass_len = buf[5] as usize;
+    if ssid_len == 0 || ssid_len > 32 || pass_len > 64 {
+        return None;
+    }
+    let ssid = core::str::from_utf8(&buf[6..6 + ssid_len]).ok()?;
+    let password = core::str::from_utf8(&buf[38..38 + pass_len]).ok()?;
+    Some(Credentials {
+        ssid: unwrap!(ssid.try_into()),
+        password: unwrap!(password.try_into()),
+    })
+}
+
+/// Size of the record written to flash: the fixed layout rounded up to a full RP2040
+/// program page, since `blocking_write` rejects non-page-multiple lengths.
+const RECORD_SIZE: usize = 256;
+
+/// Persist credentials to the reserved sector, erasing it first.
+fn store_credentials(
+    flash: &mut Flash<'static, FLASH, Async, FLASH_SIZE>,
+    ssid: &str,
+    password: &str,
+) -> Result<(), ()> {
+    let mut buf = [0u8; RECORD_SIZE];
+    buf[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    buf[4] = ssid.len() as u8;
+    buf[5] = password.len() as u8;
+    buf[6..6 + ssid.len()].copy_from_slice(ssid.as_bytes());
+    buf[38..38 + password.len()].copy_from_slice(password.as_bytes());
+
+    flash
+        .blocking_erase(CREDS_OFFSET, CREDS_OFFSET + ERASE_SIZE)
+        .map_err(|_| ())?;
+    flash.blocking_write(CREDS_OFFSET, &buf).map_err(|_| ())
+}
+
+/// Bring up the provisioning AP, collect credentials over HTTP, persist them and reset.
+///
+/// Never returns: once valid credentials are stored the chip is reset so `main`
+/// runs the station path with the freshly stored credentials.
+///
+/// The stack is configured for DHCP client operation in `main`, which is useless
+/// while we *are* the AP: the device has no address and AP clients have nowhere to
+/// get one, so `accept(80)` would never fire. We therefore switch to a static address
+/// and run a one-lease DHCP server for the duration; the reset below drops us back
+/// onto the DHCP-client config for station mode.
+pub async fn run_provisioning(
+    control: &mut cyw43::Control<'static>,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    flash: &mut Flash<'static, FLASH, Async, FLASH_SIZE>,
+) -> ! {
+    info!("provisioning: starting AP '{}'", AP_SSID);
+    control.start_ap_open(AP_SSID, 5).await;
+    stack.set_config_v4(ConfigV4::Static(StaticConfigV4 {
+        address: Ipv4Cidr::new(AP_ADDRESS, 24),
+        gateway: Some(AP_ADDRESS),
+        dns_servers: Vec::new(),
+    }));
+
+    // Serve the form and hand out leases concurrently; `serve_http` resets the chip
+    // on success, so neither branch ever returns.
+    match select(serve_http(stack, flash), serve_dhcp(stack)).await {
+        Either::First(never) | Either::Second(never) => never,
+    }
+}
+
+/// Accept connections on TCP:80, serve the form and persist submitted credentials.
+async fn serve_http(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    flash: &mut Flash<'static, FLASH, Async, FLASH_SIZE>,
+) -> ! {
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut buf = [0; 1024];
+    let mut reply = String::<512>::new();
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(30)));
+        if let Err(e) = socket.accept(80).await {
+            warn!("provisioning: accept error: {:?}", e);
+            continue;
+        }
+
+        let n = match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => {
+                socket.close();
+                continue;
+            }
+            Ok(n) => n,
+        };
+        let request = core::str::from_utf8(&buf[..n]).unwrap_or("");
+
+        if let Some((ssid, password)) = parse_form(request) {
+            if store_credentials(flash, ssid, password).is_ok() {
+                let _ = socket
+                    .write_all(response(&mut reply, "Saved. Rebooting into station mode."))
+                    .await;
+                let _ = socket.flush().await;
+                info!("provisioning: credentials stored, resetting");
+                SCB::sys_reset();
+            } else {
+                let _ = socket
+                    .write_all(response(&mut reply, "Flash write failed."))
+                    .await;
+            }
+        } else {
+            let _ = socket.write_all(response(&mut reply, FORM)).await;
+        }
+        socket.close();
+    }
+}
+
+/// A minimal DHCP server: offers the single [`AP_CLIENT`] address to whichever client
+/// associates with the AP, enough for it to reach the form at [`AP_ADDRESS`].
+async fn serve_dhcp(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut buf = [0; 576];
+    let mut reply = [0; 576];
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    unwrap!(socket.bind(67));
+
+    loop {
+        let n = match socket.recv_from(&mut buf).await {
+            Ok((n, _)) => n,
+            Err(e) => {
+                warn!("provisioning: dhcp recv error: {:?}", e);
+                continue;
+            }
+        };
+        if let Some(len) = build_dhcp_reply(&buf[..n], &mut reply) {
+            // AP clients have no address yet, so the reply must go to the broadcast
+            // address on the client port.
+            let endpoint = IpEndpoint::new(Ipv4Address::new(255, 255, 255, 255).into(), 68);
+            if let Err(e) = socket.send_to(&reply[..len], endpoint).await {
+                warn!("provisioning: dhcp send error: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Build a BOOTREPLY for a DISCOVER/REQUEST, returning the encoded length.
+///
+/// Only the single fixed lease is modelled, which is all the provisioning AP needs.
+fn build_dhcp_reply(req: &[u8], out: &mut [u8]) -> Option<usize> {
+    if req.len() < 240 || req[236..240] != [99, 130, 83, 99] {
+        return None;
+    }
+    let reply_type = match *find_option(&req[240..], 53)?.first()? {
+        1 => 2, // DISCOVER -> OFFER
+        3 => 5, // REQUEST  -> ACK
+        _ => return None,
+    };
+
+    out.iter_mut().for_each(|b| *b = 0);
+    out[0] = 2; // BOOTREPLY
+    out[1] = req[1]; // htype
+    out[2] = req[2]; // hlen
+    out[4..8].copy_from_slice(&req[4..8]); // xid
+    out[10..12].copy_from_slice(&req[10..12]); // flags (preserve broadcast bit)
+    out[16..20].copy_from_slice(&AP_CLIENT.octets()); // yiaddr
+    out[20..24].copy_from_slice(&AP_ADDRESS.octets()); // siaddr
+    out[28..44].copy_from_slice(&req[28..44]); // chaddr
+    out[236..240].copy_from_slice(&[99, 130, 83, 99]); // magic cookie
+
+    let mut i = 240;
+    let opt = |buf: &mut [u8], i: &mut usize, code: u8, data: &[u8]| {
+        buf[*i] = code;
+        buf[*i + 1] = data.len() as u8;
+        buf[*i + 2..*i + 2 + data.len()].copy_from_slice(data);
+        *i += 2 + data.len();
+    };
+    opt(out, &mut i, 53, &[reply_type]);
+    opt(out, &mut i, 54, &AP_ADDRESS.octets());
+    opt(out, &mut i, 51, &DHCP_LEASE_SECS.to_be_bytes());
+    opt(out, &mut i, 1, &[255, 255, 255, 0]);
+    opt(out, &mut i, 3, &AP_ADDRESS.octets());
+    out[i] = 255; // end
+    i += 1;
+    Some(i)
+}
+
+/// Return the value bytes of the first DHCP option matching `code`.
+fn find_option(options: &[u8], code: u8) -> Option<&[u8]> {
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            255 => return None, // end
+            0 => i += 1,        // pad
+            c => {
+                let len = *options.get(i + 1)? as usize;
+                let value = options.get(i + 2..i + 2 + len)?;
+                if c == code {
+                    return Some(value);
+                }
+                i += 2 + len;
+            }
+        }
+    }
+    None
+}
+
+/// Format a tiny HTTP/1.0 response for the given body into `out` and return its bytes.
+fn response<'a>(out: &'a mut String<512>, body: &str) -> &'a [u8] {
+    out.clear();
+    let _ = write!(
+        out,
+        "HTTP/1.0 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n{}",
+        body
+    );
+    out.as_bytes()
+}
+
+const FORM: &str = "<form method=post><input name=ssid placeholder=SSID>\
+<input name=pass placeholder=Password type=password>\
+<button>Join</button></form>";
+
+/// Pull `ssid` and `pass` out of a urlencoded POST body.
+fn parse_form(request: &str) -> Option<(&str, &str)> {
+    let body = request.split("\r\n\r\n").nth(1)?;
+    let mut ssid = None;
+    let mut pass = None;
+    for field in body.split('&') {
+        match field.split_once('=') {
+            Some(("ssid", v)) => ssid = Some(v.trim_end_matches(|c| c == '\0' || c == '\n')),
+            Some(("pass", v)) => pass = Some(v.trim_end_matches(|c| c == '\0' || c == '\n')),
+            _ => {}
+        }
+    }
+    match (ssid, pass) {
+        (Some(s), Some(p)) if !s.is_empty() && s.len() <= 32 && p.len() <= 64 => Some((s, p)),
+        _ => None,
+    }
+}
@@ -0,0 +1,128 @@
+//! Temperature controller shared between the UART CLI, the MQTT subsystem and the
+//! control task.
+//!
+//! [`TempController`] owns the live setpoint, the compressor anti-short-cycle timings
+//! and the operating mode. It lives behind the [`SharedController`](crate::SharedController)
+//! mutex so local (UART) and remote (MQTT) writers coordinate on one instance instead
+//! of the old raw-pointer aliasing; the accessors below are the only way to touch its
+//! state. [`temp_controller_task`] samples the DHT11, publishes the readings into
+//! `SHARED_TEMP`/`SHARED_HUMID` and drives the compressor relay from that state.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use defmt::Format;
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::peripherals::PIN_13;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::dht11::DHT11;
+use crate::SharedController;
+
+/// Most recent temperature reading, in whole degrees Celsius.
+pub static SHARED_TEMP: AtomicI32 = AtomicI32::new(0);
+/// Most recent relative-humidity reading, in whole percent.
+pub static SHARED_HUMID: AtomicI32 = AtomicI32::new(0);
+
+/// How often the DHT11 is sampled and the relay re-evaluated.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Operating mode of the controller.
+#[derive(Clone, Copy, PartialEq, Eq, Format)]
+pub enum Mode {
+    /// Compressor held off regardless of temperature.
+    Off,
+    /// Cool towards the setpoint (compressor on while above it).
+    Cool,
+}
+
+/// Live controller state, guarded by [`SharedController`](crate::SharedController).
+pub struct TempController {
+    setpoint: i32,
+    hysteresis_on: Duration,
+    hysteresis_off: Duration,
+    mode: Mode,
+}
+
+impl TempController {
+    /// Create a controller with an initial setpoint and the minimum compressor
+    /// on/off times used to avoid short-cycling.
+    pub fn new(setpoint: i32, hysteresis_on: Duration, hysteresis_off: Duration) -> Self {
+        Self {
+            setpoint,
+            hysteresis_on,
+            hysteresis_off,
+            mode: Mode::Cool,
+        }
+    }
+
+    /// Current target temperature in degrees Celsius.
+    pub fn setpoint(&self) -> i32 {
+        self.setpoint
+    }
+
+    /// Update the target temperature.
+    pub fn set_setpoint(&mut self, setpoint: i32) {
+        self.setpoint = setpoint;
+    }
+
+    /// Minimum compressor on/off durations, as `(on, off)`.
+    pub fn hysteresis(&self) -> (Duration, Duration) {
+        (self.hysteresis_on, self.hysteresis_off)
+    }
+
+    /// Update the minimum compressor on/off durations.
+    pub fn set_hysteresis(&mut self, on: Duration, off: Duration) {
+        self.hysteresis_on = on;
+        self.hysteresis_off = off;
+    }
+
+    /// Current operating mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Change the operating mode.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+}
+
+/// Sample the DHT11, update the shared readings and drive the compressor relay.
+#[embassy_executor::task]
+pub async fn temp_controller_task(
+    mut dht11: DHT11,
+    controller: &'static SharedController,
+    relay_pin: PIN_13,
+) -> ! {
+    let mut relay = Output::new(relay_pin, Level::Low);
+    let mut compressor_on = false;
+    let mut last_switch = Instant::now();
+
+    loop {
+        let (temperature, humidity) = dht11.get_temperature_humidity();
+        SHARED_TEMP.store(temperature, Ordering::Relaxed);
+        SHARED_HUMID.store(humidity, Ordering::Relaxed);
+
+        // Take the current targets under the lock, then release it before touching
+        // hardware so the CLI/MQTT writers are never blocked on GPIO.
+        let (setpoint, mode, (hysteresis_on, hysteresis_off)) = {
+            let controller = controller.lock().await;
+            (controller.setpoint(), controller.mode(), controller.hysteresis())
+        };
+
+        let want_on = mode == Mode::Cool && temperature > setpoint;
+        let elapsed = last_switch.elapsed();
+        let min_dwell = if compressor_on {
+            hysteresis_on
+        } else {
+            hysteresis_off
+        };
+        if want_on != compressor_on && elapsed >= min_dwell {
+            compressor_on = want_on;
+            relay.set_level(if compressor_on { Level::High } else { Level::Low });
+            last_switch = Instant::now();
+        }
+
+        Timer::after(SAMPLE_INTERVAL).await;
+    }
+}
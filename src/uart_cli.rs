@@ -0,0 +1,121 @@
+//! UART command-line interface.
+//!
+//! A small line-oriented CLI on UART0 for local operation: report the latest readings
+//! and adjust the controller. Every write goes through the shared
+//! [`SharedController`](crate::SharedController) mutex, so the CLI and the MQTT
+//! subsystem drive the same controller instance without racing.
+
+use core::fmt::Write as _;
+use core::sync::atomic::Ordering;
+
+use defmt::*;
+use embassy_net::Stack;
+use embassy_rp::peripherals::UART0;
+use embassy_rp::uart::{self, Uart};
+use embassy_time::Duration;
+use embedded_io_async::{Read, Write};
+use heapless::String;
+
+use crate::net_backend::NetDevice;
+use crate::temp_controller::{Mode, SHARED_HUMID, SHARED_TEMP};
+use crate::SharedController;
+
+#[embassy_executor::task]
+pub async fn uart_cli(
+    mut uart: Uart<'static, UART0, uart::Async>,
+    _stack: &'static Stack<NetDevice>,
+    controller: &'static SharedController,
+) -> ! {
+    let mut line = String::<64>::new();
+    let mut byte = [0u8; 1];
+    let mut overflowed = false;
+    loop {
+        if uart.read(&mut byte).await.is_err() {
+            continue;
+        }
+        match byte[0] {
+            b'\r' | b'\n' => {
+                if !line.is_empty() && !overflowed {
+                    handle_command(&mut uart, controller, line.as_str()).await;
+                }
+                line.clear();
+                overflowed = false;
+            }
+            // Overlong input is dropped rather than silently truncated mid-command.
+            c => {
+                if !overflowed && line.push(c as char).is_err() {
+                    line.clear();
+                    overflowed = true;
+                }
+            }
+        }
+    }
+}
+
+/// Parse and apply a single command line, writing a short reply back over UART.
+async fn handle_command(
+    uart: &mut Uart<'static, UART0, uart::Async>,
+    controller: &'static SharedController,
+    line: &str,
+) {
+    let mut reply = String::<96>::new();
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some("get") => {
+            let temperature = SHARED_TEMP.load(Ordering::Relaxed);
+            let humidity = SHARED_HUMID.load(Ordering::Relaxed);
+            let setpoint = controller.lock().await.setpoint();
+            let _ = write!(
+                reply,
+                "temp={} humid={} setpoint={}\r\n",
+                temperature, humidity, setpoint
+            );
+        }
+        Some("set") => match parts.next().and_then(|v| v.parse::<i32>().ok()) {
+            Some(setpoint) => {
+                controller.lock().await.set_setpoint(setpoint);
+                let _ = write!(reply, "setpoint={}\r\n", setpoint);
+            }
+            None => {
+                let _ = write!(reply, "usage: set <celsius>\r\n");
+            }
+        },
+        Some("mode") => match parts.next() {
+            Some("cool") => {
+                controller.lock().await.set_mode(Mode::Cool);
+                let _ = write!(reply, "mode=cool\r\n");
+            }
+            Some("off") => {
+                controller.lock().await.set_mode(Mode::Off);
+                let _ = write!(reply, "mode=off\r\n");
+            }
+            _ => {
+                let _ = write!(reply, "usage: mode <cool|off>\r\n");
+            }
+        },
+        Some("hyst") => {
+            match (
+                parts.next().and_then(|v| v.parse::<u64>().ok()),
+                parts.next().and_then(|v| v.parse::<u64>().ok()),
+            ) {
+                (Some(on), Some(off)) => {
+                    controller
+                        .lock()
+                        .await
+                        .set_hysteresis(Duration::from_secs(on), Duration::from_secs(off));
+                    let _ = write!(reply, "hyst on={}s off={}s\r\n", on, off);
+                }
+                _ => {
+                    let _ = write!(reply, "usage: hyst <on_secs> <off_secs>\r\n");
+                }
+            }
+        }
+        _ => {
+            let _ = write!(
+                reply,
+                "commands: get | set <c> | mode <cool|off> | hyst <on> <off>\r\n"
+            );
+        }
+    }
+    let _ = uart.write_all(reply.as_bytes()).await;
+}